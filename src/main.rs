@@ -1,17 +1,24 @@
 use anyhow::{Result, anyhow};
 use clap::{Parser, builder::ValueParser};
+use humantime::Duration as HumanDuration;
 use k8s_openapi::api::{batch::v1::Job, core::v1::Pod};
 use kube::{
     Api, Client, Config, Error,
     api::{Patch, PatchParams},
-    core::{ObjectMeta, PartialObjectMetaExt},
+    config::{KubeConfigOptions, Kubeconfig},
+    core::{DynamicObject, ObjectMeta, PartialObjectMeta, PartialObjectMetaExt, TypeMeta},
+    discovery::{ApiResource, Discovery, Scope},
 };
 use regex::Regex;
 use std::ffi::OsStr;
+use std::path::PathBuf;
 use std::process::ExitCode;
-use std::time::Duration;
 use tokio::process::Command;
 
+/// Exit code returned when the wrapped command is killed for running
+/// past `--command-timeout`.
+const COMMAND_TIMEOUT_EXIT_CODE: u8 = 124;
+
 /// Parse a kubernetes resource label
 fn parse_label(v: &str) -> Result<(String, String)> {
     // Reference:
@@ -32,91 +39,638 @@ fn parse_label(v: &str) -> Result<(String, String)> {
     }
 }
 
+/// Reference:
+/// https://kubernetes.io/docs/concepts/overview/working-with-objects/labels/#syntax-and-character-set
+const LABEL_KEY_PATTERN: &str = r"^([a-z0-9A-Z.]{1,253}/)?[a-z0-9A-Z\-_.]{1,63}$";
+
+/// Parse a kubernetes resource annotation. Unlike labels, annotation
+/// values allow arbitrary strings, so only the key is validated.
+fn parse_annotation(v: &str) -> Result<(String, String)> {
+    let (key, value) = v
+        .split_once("=")
+        .ok_or_else(|| anyhow!("Invalid annotation value"))?;
+    if !Regex::new(LABEL_KEY_PATTERN)?.is_match(key) {
+        Err(anyhow!("Invalid annotation key"))
+    } else {
+        Ok((String::from(key), String::from(value)))
+    }
+}
+
+/// Parse a bare kubernetes label key, as used by `--if-absent`.
+fn parse_label_key(v: &str) -> Result<String> {
+    if !Regex::new(LABEL_KEY_PATTERN)?.is_match(v) {
+        Err(anyhow!("Invalid label key"))
+    } else {
+        Ok(String::from(v))
+    }
+}
+
+/// Decide whether a guarded patch should proceed, given the resource's
+/// current labels and the `--if-absent`/`--if-label-equals` guards.
+/// With neither guard set, patching always proceeds.
+fn guard_satisfied(
+    current_labels: &std::collections::BTreeMap<String, String>,
+    if_absent: Option<&str>,
+    if_label_equals: Option<&(String, String)>,
+) -> bool {
+    if let Some(key) = if_absent {
+        if current_labels.contains_key(key) {
+            return false;
+        }
+    }
+    if let Some((key, value)) = if_label_equals {
+        if current_labels.get(key) != Some(value) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Substitute the `{exit_code}` and `{status}` template tokens in a
+/// label value with the wrapped command's exit status.
+fn render_label_value(value: &str, exit_code: i32, success: bool) -> String {
+    value
+        .replace("{exit_code}", &exit_code.to_string())
+        .replace("{status}", if success { "success" } else { "failure" })
+}
+
+/// Parse a kubernetes resource label whose value may contain the
+/// `{exit_code}` and `{status}` template tokens. The value is checked
+/// as `parse_label` would check it once the tokens are rendered.
+fn parse_label_template(v: &str) -> Result<(String, String)> {
+    let (key, value) = v
+        .split_once("=")
+        .ok_or_else(|| anyhow!("Invalid label value"))?;
+    parse_label(&format!("{key}={}", render_label_value(value, 255, false)))?;
+    Ok((String::from(key), String::from(value)))
+}
+
 #[derive(Clone, Debug)]
 enum ResourceKind {
     Pod,
     Job,
+    /// Anything else, resolved against the cluster's API discovery at
+    /// patch time. `group`/`version` are only set when given explicitly
+    /// as `group/version/kind/name`. If `kind` alone resolves in more
+    /// than one API group, discovery refuses the guess and errors out
+    /// asking for `group/version/kind/name` instead.
+    Dynamic {
+        group: Option<String>,
+        version: Option<String>,
+        kind: String,
+    },
 }
 
-/// Parse a kubernetes resource identifier, limited to jobs and pods only
+/// Parse a kubernetes resource identifier. `pod/name` and `job/name` take
+/// the typed fast paths; `kind/name` or `group/version/kind/name` fall
+/// through to API discovery so any labelable resource (Deployment,
+/// StatefulSet, CronJob, a CRD, ...) can be targeted.
 fn parse_resource(v: &str) -> Result<(ResourceKind, String)> {
-    let mut parts = v.split("/");
-    let kind = match parts.next() {
-        Some("job") => Ok(ResourceKind::Job),
-        Some("pod") => Ok(ResourceKind::Pod),
+    let parts: Vec<&str> = v.split("/").collect();
+    match parts.as_slice() {
+        ["job", name] if !name.is_empty() => Ok((ResourceKind::Job, String::from(*name))),
+        ["pod", name] if !name.is_empty() => Ok((ResourceKind::Pod, String::from(*name))),
+        [group, version, kind, name] if !name.is_empty() => Ok((
+            ResourceKind::Dynamic {
+                group: Some(String::from(*group)),
+                version: Some(String::from(*version)),
+                kind: String::from(*kind),
+            },
+            String::from(*name),
+        )),
+        [kind, name] if !name.is_empty() => Ok((
+            ResourceKind::Dynamic {
+                group: None,
+                version: None,
+                kind: String::from(*kind),
+            },
+            String::from(*name),
+        )),
         _ => Err(anyhow!("invalid or missing resource kind")),
-    }?;
-    Ok((kind, parts.collect::<Vec<_>>().join("/")))
+    }
+}
+
+/// Resolve a resource kind against the cluster's API discovery,
+/// returning the `ApiResource` and scope needed to build an
+/// `Api<DynamicObject>` for it. Without an explicit `version`, each
+/// group is searched at its most stable version only. A `kind` that
+/// resolves in more than one group is rejected rather than guessed at: which API group
+/// "wins" would otherwise depend on `Discovery`'s internal iteration
+/// order, not on any actual preference, which is the wrong kind of
+/// nondeterminism for a tool whose job is patching the right object.
+async fn discover_resource(
+    client: &Client,
+    group: Option<&str>,
+    version: Option<&str>,
+    kind: &str,
+) -> Result<(ApiResource, Scope)> {
+    let discovery = Discovery::new(client.clone()).run().await?;
+    let matches: Vec<(ApiResource, Scope)> = discovery
+        .groups()
+        .flat_map(|g| match version {
+            Some(v) => g.versioned_resources(v),
+            None => g.resources_by_stability(),
+        })
+        .filter(|(ar, _)| {
+            ar.kind.eq_ignore_ascii_case(kind)
+                && group.is_none_or(|g| ar.group == g)
+                && version.is_none_or(|v| ar.version == v)
+        })
+        .map(|(ar, caps)| (ar, caps.scope))
+        .collect();
+    match matches.as_slice() {
+        [] => Err(anyhow!("resource kind '{kind}' not found via API discovery")),
+        [(ar, scope)] => Ok((ar.clone(), *scope)),
+        _ => {
+            let groups: Vec<String> = matches
+                .iter()
+                .map(|(ar, _)| format!("{}/{}", ar.group, ar.version))
+                .collect();
+            Err(anyhow!(
+                "resource kind '{kind}' is ambiguous across {}; specify group/version/kind/name to disambiguate",
+                groups.join(", ")
+            ))
+        }
+    }
+}
+
+/// Fetch a resource's current metadata with `Api::get_metadata`, which
+/// pulls just the `ObjectMeta` rather than the whole object, and decide
+/// whether the guarded patch should proceed.
+async fn guard_allows<K>(
+    api: &Api<K>,
+    name: &str,
+    if_absent: Option<&str>,
+    if_label_equals: Option<&(String, String)>,
+) -> Result<bool, Error>
+where
+    K: kube::Resource + Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    if if_absent.is_none() && if_label_equals.is_none() {
+        return Ok(true);
+    }
+    let current = api.get_metadata(name).await?;
+    let current_labels = current.metadata.labels.unwrap_or_default();
+    Ok(guard_satisfied(&current_labels, if_absent, if_label_equals))
 }
 
-/// Wrap a command in a post-success handler that updates a K8s
-/// resource label.
+/// Wrap a command and patch a K8s resource's labels and annotations
+/// with its outcome, using the success or failure set depending on
+/// whether the wrapped command exits zero.
 #[derive(Parser)]
 #[command(version, about)]
 pub struct Cli {
     #[arg(short, long, env = "K8S_PSL_NAMESPACE", default_value_t = String::from("default"))]
     namespace: String,
 
-    #[arg(short, long, env = "K8S_PSL_LABEL", value_parser = ValueParser::new(parse_label))]
-    label: (String, String),
+    #[arg(long, env = "K8S_PSL_CONNECT_TIMEOUT", default_value = "15s")]
+    connect_timeout: HumanDuration,
+
+    #[arg(long, env = "K8S_PSL_READ_TIMEOUT", default_value = "15s")]
+    read_timeout: HumanDuration,
+
+    #[arg(long, env = "K8S_PSL_WRITE_TIMEOUT", default_value = "15s")]
+    write_timeout: HumanDuration,
+
+    /// Overall timeout for the wrapped command; it is killed and
+    /// `k8s-psl` exits 124 if it runs past this duration.
+    #[arg(long, env = "K8S_PSL_COMMAND_TIMEOUT")]
+    command_timeout: Option<HumanDuration>,
+
+    /// Path to a kubeconfig file to read instead of the default
+    /// locations. Setting this (or `--context`/`--cluster`/`--user`)
+    /// switches from `Config::infer()` to reading a kubeconfig.
+    #[arg(long, env = "K8S_PSL_KUBECONFIG")]
+    kubeconfig: Option<PathBuf>,
+
+    /// kubeconfig context to use instead of inferring one from the
+    /// environment or in-cluster config.
+    #[arg(long, env = "K8S_PSL_CONTEXT")]
+    context: Option<String>,
+
+    /// kubeconfig cluster to use instead of inferring one.
+    #[arg(long, env = "K8S_PSL_CLUSTER")]
+    cluster: Option<String>,
+
+    /// kubeconfig user to use instead of inferring one.
+    #[arg(long, env = "K8S_PSL_USER")]
+    user: Option<String>,
+
+    /// Label applied when the wrapped command succeeds. May be given
+    /// more than once. Values may contain `{exit_code}` and `{status}`
+    /// tokens.
+    #[arg(short, long, env = "K8S_PSL_LABEL", required = true, value_parser = ValueParser::new(parse_label_template))]
+    label: Vec<(String, String)>,
+
+    /// Annotation applied when the wrapped command succeeds. May be
+    /// given more than once. Values may contain `{exit_code}` and
+    /// `{status}` tokens.
+    #[arg(long, env = "K8S_PSL_ANNOTATION", value_parser = ValueParser::new(parse_annotation))]
+    annotation: Vec<(String, String)>,
+
+    /// Label applied when the wrapped command fails, instead of
+    /// skipping the patch. May be given more than once. Values may
+    /// contain `{exit_code}` and `{status}` tokens.
+    #[arg(long, env = "K8S_PSL_FAILURE_LABEL", value_parser = ValueParser::new(parse_label_template))]
+    failure_label: Vec<(String, String)>,
+
+    /// Annotation applied when the wrapped command fails, instead of
+    /// skipping the patch. May be given more than once. Values may
+    /// contain `{exit_code}` and `{status}` tokens.
+    #[arg(long, env = "K8S_PSL_FAILURE_ANNOTATION", value_parser = ValueParser::new(parse_annotation))]
+    failure_annotation: Vec<(String, String)>,
+
+    /// Only apply the patch if this label key is not already present
+    /// on the resource; otherwise exit 0 as a no-op. Conflicts with
+    /// `--if-label-equals`.
+    #[arg(long, env = "K8S_PSL_IF_ABSENT", value_parser = ValueParser::new(parse_label_key), conflicts_with = "if_label_equals")]
+    if_absent: Option<String>,
+
+    /// Only apply the patch if the resource's current label matches
+    /// `key=value`; otherwise exit 0 as a no-op. Conflicts with
+    /// `--if-absent`.
+    #[arg(long, env = "K8S_PSL_IF_LABEL_EQUALS", value_parser = ValueParser::new(parse_label), conflicts_with = "if_absent")]
+    if_label_equals: Option<(String, String)>,
 
     #[arg(value_parser = ValueParser::new(parse_resource))]
     resource: (ResourceKind, String),
 
+    /// Increase log verbosity (-v for info, -vv for debug, -vvv for
+    /// trace). Overridden by `RUST_LOG` when it is set.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
     #[arg(trailing_var_arg = true)]
     command: Vec<String>,
 }
 
-/// Patch a resource of the given kind, so that the given label is
-/// added to its metadata. Return an ExitCode somewhat representing
-/// the result of the patching.
+/// Build the `ObjectMeta` carrying all the given labels and
+/// annotations, leaving either field unset when no pairs were given.
+macro_rules! patch_metadata {
+    ($labels:expr, $annotations:expr) => {{
+        let labels: std::collections::BTreeMap<String, String> = $labels.into_iter().collect();
+        let annotations: std::collections::BTreeMap<String, String> =
+            $annotations.into_iter().collect();
+        ObjectMeta {
+            labels: (!labels.is_empty()).then_some(labels),
+            annotations: (!annotations.is_empty()).then_some(annotations),
+            ..Default::default()
+        }
+    }};
+}
+
+/// Map the result of a metadata fetch or patch to an ExitCode: 66 for
+/// a Kubernetes API error, 68 for a service-level error (e.g. the
+/// apiserver being unreachable), 1 for anything else, 0 on success.
+/// Logs the distinct `Error` branches at error level, and success at
+/// info level, tagged with the `stage` that produced the result.
+fn exit_code_for_result<T>(stage: &'static str, result: Result<T, Error>) -> ExitCode {
+    match result {
+        Err(Error::Api(e)) => {
+            tracing::error!(stage, error = %e, "Kubernetes API error");
+            ExitCode::from(66)
+        }
+        Err(Error::Service(e)) => {
+            tracing::error!(stage, error = %e, "service error");
+            ExitCode::from(68)
+        }
+        Err(e) => {
+            tracing::error!(stage, error = %e, "unexpected error");
+            ExitCode::from(1)
+        }
+        Ok(_) => {
+            tracing::info!(stage, "ok");
+            ExitCode::from(0)
+        }
+    }
+}
+
+/// Patch a resource of the given kind, so that the given labels and
+/// annotations are added to its metadata in a single server-side-apply
+/// patch, after checking the `--if-absent`/`--if-label-equals` guards.
+/// Return an ExitCode somewhat representing the result of the patching.
 macro_rules! patch_resource {
-    ($kind:ty, $client:expr, $ns:expr, $name:expr, $label:expr) => {{
+    ($kind:ty, $client:expr, $ns:expr, $name:expr, $labels:expr, $annotations:expr, $if_absent:expr, $if_label_equals:expr) => {{
         let api: Api<$kind> = Api::namespaced($client, $ns);
-        let metadata = ObjectMeta {
-            labels: Some([$label].into()),
-            ..Default::default()
+        match guard_allows(&api, $name, $if_absent, $if_label_equals).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::info!("skipping patch: guard not satisfied");
+                return Ok(ExitCode::from(0));
+            }
+            Err(e) => return Ok(exit_code_for_result::<()>("get_metadata", Err(e))),
         }
-        .into_request_partial::<$kind>();
+        let metadata = patch_metadata!($labels, $annotations).into_request_partial::<$kind>();
         let params = PatchParams::apply("k8s-psl");
         let result = api
             .patch_metadata($name, &params, &Patch::Apply(&metadata))
             .await;
-        match result {
-            Err(Error::Api(_)) => Ok(ExitCode::from(66)),
-            Err(Error::Service(_)) => Ok(ExitCode::from(68)),
-            Err(_) => Ok(ExitCode::from(1)),
-            _ => Ok(ExitCode::from(0)),
+        Ok(exit_code_for_result("patch", result))
+    }};
+}
+
+/// Patch a dynamic resource (resolved through API discovery) so that
+/// the given labels and annotations are added to its metadata, using
+/// the same guarded server-side-apply flow as `patch_resource!`.
+macro_rules! patch_dynamic_resource {
+    ($client:expr, $ns:expr, $ar:expr, $scope:expr, $name:expr, $labels:expr, $annotations:expr, $if_absent:expr, $if_label_equals:expr) => {{
+        let api: Api<DynamicObject> = match $scope {
+            Scope::Namespaced => Api::namespaced_with($client, $ns, &$ar),
+            Scope::Cluster => Api::all_with($client, &$ar),
+        };
+        match guard_allows(&api, $name, $if_absent, $if_label_equals).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::info!("skipping patch: guard not satisfied");
+                return Ok(ExitCode::from(0));
+            }
+            Err(e) => return Ok(exit_code_for_result::<()>("get_metadata", Err(e))),
         }
+        // `DynamicObject`'s `Resource::DynamicType` is `ApiResource`, not
+        // `()`, so `ObjectMeta::into_request_partial` (which only works
+        // for `K: Resource<DynamicType = ()>`) doesn't apply here; fill
+        // in the `TypeMeta` by hand from the discovered `ApiResource`.
+        let metadata = PartialObjectMeta::<DynamicObject> {
+            types: Some(TypeMeta {
+                api_version: $ar.api_version.clone(),
+                kind: $ar.kind.clone(),
+            }),
+            metadata: patch_metadata!($labels, $annotations),
+            _phantom: std::marker::PhantomData,
+        };
+        let params = PatchParams::apply("k8s-psl");
+        let result = api
+            .patch_metadata($name, &params, &Patch::Apply(&metadata))
+            .await;
+        Ok(exit_code_for_result("patch", result))
     }};
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
-    let mut k8s_config = Config::infer().await?;
-    k8s_config.connect_timeout = Some(Duration::from_secs(15));
-    k8s_config.read_timeout = Some(Duration::from_secs(15));
-    k8s_config.write_timeout = Some(Duration::from_secs(15));
+
+    let default_level = match cli.verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_level.to_string()));
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_env_filter(env_filter)
+        .with_writer(std::io::stderr)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let mut k8s_config = if cli.context.is_some()
+        || cli.cluster.is_some()
+        || cli.user.is_some()
+        || cli.kubeconfig.is_some()
+    {
+        let options = KubeConfigOptions {
+            context: cli.context.clone(),
+            cluster: cli.cluster.clone(),
+            user: cli.user.clone(),
+        };
+        match &cli.kubeconfig {
+            Some(path) => {
+                let kubeconfig = Kubeconfig::read_from(path)?;
+                Config::from_custom_kubeconfig(kubeconfig, &options).await?
+            }
+            None => Config::from_kubeconfig(&options).await?,
+        }
+    } else {
+        Config::infer().await?
+    };
+    k8s_config.connect_timeout = Some(cli.connect_timeout.into());
+    k8s_config.read_timeout = Some(cli.read_timeout.into());
+    k8s_config.write_timeout = Some(cli.write_timeout.into());
     let k8s_client = Client::try_from(k8s_config)?;
 
+    tracing::info!(command = ?cli.command, "running wrapped command");
     let mut command_parts = cli.command.iter();
-    let status = Command::new(OsStr::new(
+    let mut child = Command::new(OsStr::new(
         command_parts.next().ok_or(anyhow!("Missing command"))?,
     ))
     .args(command_parts)
-    .status()
-    .await?;
-    if !status.success() {
-        return Ok(ExitCode::from(u8::try_from(status.code().unwrap_or(1))?));
-    }
+    .spawn()?;
+    let status = match cli.command_timeout {
+        Some(command_timeout) => {
+            match tokio::time::timeout(command_timeout.into(), child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    tracing::error!(
+                        timeout = %command_timeout,
+                        "wrapped command exceeded --command-timeout, killing it"
+                    );
+                    child.kill().await?;
+                    return Ok(ExitCode::from(COMMAND_TIMEOUT_EXIT_CODE));
+                }
+            }
+        }
+        None => child.wait().await?,
+    };
+    let exit_code = status.code().unwrap_or(1);
+    let success = status.success();
+    tracing::info!(exit_code, success, "wrapped command finished");
+    let (labels, annotations) = if success {
+        (cli.label, cli.annotation)
+    } else if !cli.failure_label.is_empty() || !cli.failure_annotation.is_empty() {
+        (cli.failure_label, cli.failure_annotation)
+    } else {
+        return Ok(ExitCode::from(u8::try_from(exit_code)?));
+    };
+    let labels: Vec<(String, String)> = labels
+        .into_iter()
+        .map(|(k, v)| (k, render_label_value(&v, exit_code, success)))
+        .collect();
+    let annotations: Vec<(String, String)> = annotations
+        .into_iter()
+        .map(|(k, v)| (k, render_label_value(&v, exit_code, success)))
+        .collect();
+
+    let if_absent = cli.if_absent.as_deref();
+    let if_label_equals = cli.if_label_equals.as_ref();
+
+    tracing::info!(
+        namespace = %cli.namespace,
+        resource = ?cli.resource,
+        ?labels,
+        ?annotations,
+        "patching resource metadata"
+    );
 
-    match cli.resource.0 {
+    match &cli.resource.0 {
         ResourceKind::Pod => {
-            patch_resource!(Pod, k8s_client, &cli.namespace, &cli.resource.1, cli.label)
+            patch_resource!(
+                Pod,
+                k8s_client,
+                &cli.namespace,
+                &cli.resource.1,
+                labels,
+                annotations,
+                if_absent,
+                if_label_equals
+            )
         }
         ResourceKind::Job => {
-            patch_resource!(Job, k8s_client, &cli.namespace, &cli.resource.1, cli.label)
+            patch_resource!(
+                Job,
+                k8s_client,
+                &cli.namespace,
+                &cli.resource.1,
+                labels,
+                annotations,
+                if_absent,
+                if_label_equals
+            )
         }
+        ResourceKind::Dynamic {
+            group,
+            version,
+            kind,
+        } => {
+            let (ar, scope) = discover_resource(
+                &k8s_client,
+                group.as_deref(),
+                version.as_deref(),
+                kind,
+            )
+            .await?;
+            tracing::info!(?ar, ?scope, "resolved resource kind via API discovery");
+            patch_dynamic_resource!(
+                k8s_client,
+                &cli.namespace,
+                ar,
+                scope,
+                &cli.resource.1,
+                labels,
+                annotations,
+                if_absent,
+                if_label_equals
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resource_pod() {
+        let (kind, name) = parse_resource("pod/mypod").unwrap();
+        assert!(matches!(kind, ResourceKind::Pod));
+        assert_eq!(name, "mypod");
+    }
+
+    #[test]
+    fn parse_resource_job() {
+        let (kind, name) = parse_resource("job/myjob").unwrap();
+        assert!(matches!(kind, ResourceKind::Job));
+        assert_eq!(name, "myjob");
+    }
+
+    #[test]
+    fn parse_resource_kind_only() {
+        let (kind, name) = parse_resource("deployment/myapp").unwrap();
+        assert!(matches!(
+            kind,
+            ResourceKind::Dynamic {
+                group: None,
+                version: None,
+                kind
+            } if kind == "deployment"
+        ));
+        assert_eq!(name, "myapp");
+    }
+
+    #[test]
+    fn parse_resource_group_version_kind() {
+        let (kind, name) = parse_resource("apps/v1/deployment/myapp").unwrap();
+        assert!(matches!(
+            kind,
+            ResourceKind::Dynamic {
+                group: Some(ref group),
+                version: Some(ref version),
+                kind: ref k,
+            } if group == "apps" && version == "v1" && k == "deployment"
+        ));
+        assert_eq!(name, "myapp");
+    }
+
+    #[test]
+    fn parse_resource_rejects_missing_name() {
+        assert!(parse_resource("pod/").is_err());
+        assert!(parse_resource("job/").is_err());
+        assert!(parse_resource("deployment/").is_err());
+    }
+
+    #[test]
+    fn parse_resource_rejects_malformed_three_segments() {
+        assert!(parse_resource("a/b/c").is_err());
+    }
+
+    #[test]
+    fn parse_resource_rejects_bare_kind() {
+        assert!(parse_resource("bogus").is_err());
+    }
+
+    #[test]
+    fn guard_satisfied_with_neither_guard_set() {
+        let labels = std::collections::BTreeMap::new();
+        assert!(guard_satisfied(&labels, None, None));
+    }
+
+    #[test]
+    fn guard_satisfied_if_absent() {
+        let mut labels = std::collections::BTreeMap::new();
+        assert!(guard_satisfied(&labels, Some("k"), None));
+        labels.insert(String::from("k"), String::from("v"));
+        assert!(!guard_satisfied(&labels, Some("k"), None));
+    }
+
+    #[test]
+    fn guard_satisfied_if_label_equals() {
+        let mut labels = std::collections::BTreeMap::new();
+        labels.insert(String::from("k"), String::from("v"));
+        let matching = (String::from("k"), String::from("v"));
+        assert!(guard_satisfied(&labels, None, Some(&matching)));
+        let mismatching = (String::from("k"), String::from("other"));
+        assert!(!guard_satisfied(&labels, None, Some(&mismatching)));
+        let missing_key = (String::from("other"), String::from("v"));
+        assert!(!guard_satisfied(&labels, None, Some(&missing_key)));
+    }
+
+    #[test]
+    fn render_label_value_substitutes_exit_code() {
+        assert_eq!(render_label_value("code={exit_code}", 2, false), "code=2");
+    }
+
+    #[test]
+    fn render_label_value_substitutes_status() {
+        assert_eq!(
+            render_label_value("result={status}", 0, true),
+            "result=success"
+        );
+        assert_eq!(
+            render_label_value("result={status}", 1, false),
+            "result=failure"
+        );
+    }
+
+    #[test]
+    fn render_label_value_substitutes_both_tokens() {
+        assert_eq!(
+            render_label_value("{status}-{exit_code}", 7, false),
+            "failure-7"
+        );
+    }
+
+    #[test]
+    fn render_label_value_without_tokens_is_unchanged() {
+        assert_eq!(render_label_value("static-value", 0, true), "static-value");
     }
 }